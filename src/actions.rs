@@ -1,43 +1,96 @@
+use std::collections::HashSet;
 use std::error::Error;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use futures::pin_mut;
 use futures::stream::StreamExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
 use crate::archive::{EmojiDirectory, EmojiFile};
 use crate::emoji::EmojiPaginator;
+use crate::manifest::{Manifest, ManifestRecord, Operation};
 use crate::slack::SlackClient;
 
 // See build.rs
 include!(concat!(env!("OUT_DIR"), "/emoji_standard_shortcodes.rs"));
 
+/// Default number of concurrent downloads/uploads when `--concurrency` isn't given.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
 pub async fn export<T: AsRef<str>>(
-    client: Rc<SlackClient>,
+    client: Arc<SlackClient>,
     target_directory: T,
+    concurrency: usize,
+    resume: bool,
 ) -> Result<(), Box<dyn Error>> {
     let stream = EmojiPaginator::new(client.clone(), 100).into_stream();
     pin_mut!(stream);
 
     let mut emoji_directory = EmojiDirectory::new(target_directory.as_ref());
     emoji_directory.ensure_exists().await;
+    // Shared read-only across spawned download tasks below. This depends
+    // on `EmojiFile::download_to_directory` taking the directory by shared
+    // reference (`&EmojiDirectory`) and the directory holding
+    // no mutable per-call state — it only resolves paths under a fixed
+    // root, so concurrent calls don't race on shared state.
+    let emoji_directory = Arc::new(emoji_directory);
+    let manifest = Arc::new(Manifest::load(target_directory.as_ref(), Operation::Export).await?);
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
 
     while let Some(emoji_result) = stream.next().await {
         match emoji_result {
             Ok(emoji) => {
-                EmojiFile::from(emoji)
-                    .download_to_directory(client.clone(), &mut emoji_directory)
-                    .await?
+                if resume && manifest.is_complete(&emoji.name) {
+                    continue;
+                }
+
+                let permit = semaphore.clone().acquire_owned().await?;
+                let client = client.clone();
+                let emoji_directory = emoji_directory.clone();
+                let manifest = manifest.clone();
+                tasks.spawn(async move {
+                    let emoji_file = EmojiFile::from(emoji);
+                    let result = emoji_file
+                        .download_to_directory(client, &emoji_directory)
+                        .await;
+                    manifest
+                        .append(ManifestRecord {
+                            name: emoji_file.emoji.name.clone(),
+                            filename: Some(emoji_file.filename.clone()),
+                            source_url: Some(emoji_file.emoji.url.clone()),
+                            completed: result.is_ok(),
+                        })
+                        .await?;
+                    drop(permit);
+                    result
+                });
             }
             Err(e) => eprintln!("Failed to fetch emoji list or parse response: {}", e),
         }
     }
 
+    while let Some(joined) = tasks.join_next().await {
+        match joined? {
+            Ok(()) => (),
+            Err(e) => eprintln!("Failed to export emoji: {}", e),
+        }
+    }
+
     Ok(())
 }
 
+/// Slack marks an emoji list entry as an alias by giving it a URL of this
+/// form instead of an image URL, e.g. `alias:thumbsup`.
+const ALIAS_URL_PREFIX: &str = "alias:";
+
 pub async fn import<T: AsRef<str>>(
-    client: Rc<SlackClient>,
+    client: Arc<SlackClient>,
     target_directory: T,
+    concurrency: usize,
+    resume: bool,
 ) -> Result<(), Box<dyn Error>> {
     let mut emoji_directory = EmojiDirectory::new(target_directory.as_ref());
     match emoji_directory.exists().await {
@@ -50,19 +103,134 @@ pub async fn import<T: AsRef<str>>(
         _ => (),
     };
 
+    let manifest = Arc::new(Manifest::load(target_directory.as_ref(), Operation::Import).await?);
+
     let stream = emoji_directory.stream_emoji_files();
     pin_mut!(stream);
 
+    let mut images = Vec::new();
+    let mut aliases = Vec::new();
+    let mut skipped: HashSet<String> = HashSet::new();
+
     while let Some(Ok(emoji_file)) = stream.next().await {
         if EMOJI_STANDARD_SHORTCODES.contains::<str>(&emoji_file.emoji.name) {
             eprintln!(
                 "Cannot import due to conflicting Slack short code name (Unicode emoji standard): {}",
                 emoji_file.emoji.name
             );
+            skipped.insert(emoji_file.emoji.name.clone());
+            continue;
+        }
+
+        match emoji_file.emoji.url.strip_prefix(ALIAS_URL_PREFIX) {
+            Some(alias_for) => aliases.push((emoji_file, alias_for.to_string())),
+            None => images.push(emoji_file),
+        }
+    }
+
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut uploaded_count = 0usize;
+    // Names whose image upload failed (shortcode collisions are tracked in
+    // `skipped` instead): any alias targeting one of these would fail at
+    // Slack because the target doesn't exist, so the alias pass skips them too.
+    let mut failed_uploads: HashSet<String> = HashSet::new();
+
+    let mut tasks = JoinSet::new();
+    for emoji_file in images {
+        if resume && manifest.is_complete(&emoji_file.emoji.name) {
+            uploaded_count += 1;
+            continue;
+        }
+
+        let permit = semaphore.clone().acquire_owned().await?;
+        let client = client.clone();
+        let manifest = manifest.clone();
+        tasks.spawn(async move {
+            let name = emoji_file.emoji.name.clone();
+            let result = client.upload(&emoji_file, emoji_file.filepath.clone()).await;
+            manifest
+                .append(ManifestRecord {
+                    name: emoji_file.emoji.name.clone(),
+                    filename: Some(emoji_file.filename.clone()),
+                    source_url: None,
+                    completed: result.is_ok(),
+                })
+                .await?;
+            drop(permit);
+            Ok((name, result))
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        match joined? {
+            Ok((_, Ok(()))) => uploaded_count += 1,
+            Ok((name, Err(e))) => {
+                eprintln!("Failed to import emoji: {}", e);
+                failed_uploads.insert(name);
+            }
+            Err(e) => eprintln!("Failed to import emoji: {}", e),
+        }
+    }
+
+    let mut aliased_count = 0usize;
+    let mut tasks = JoinSet::new();
+    for (emoji_file, alias_for) in aliases {
+        if skipped.contains(&alias_for) {
+            eprintln!(
+                "Skipping alias '{}': target '{}' was itself skipped due to a shortcode collision",
+                emoji_file.emoji.name, alias_for
+            );
+            skipped.insert(emoji_file.emoji.name.clone());
+            continue;
+        }
+
+        if failed_uploads.contains(&alias_for) {
+            eprintln!(
+                "Skipping alias '{}': target '{}' failed to upload",
+                emoji_file.emoji.name, alias_for
+            );
+            skipped.insert(emoji_file.emoji.name.clone());
+            continue;
+        }
+
+        if resume && manifest.is_complete(&emoji_file.emoji.name) {
+            aliased_count += 1;
             continue;
         }
+
+        let permit = semaphore.clone().acquire_owned().await?;
+        let client = client.clone();
+        let manifest = manifest.clone();
+        tasks.spawn(async move {
+            let result = client.add_alias(emoji_file.emoji.name.clone(), alias_for).await;
+            manifest
+                .append(ManifestRecord {
+                    name: emoji_file.emoji.name.clone(),
+                    filename: None,
+                    source_url: None,
+                    completed: result.is_ok(),
+                })
+                .await?;
+            drop(permit);
+            result
+        });
     }
 
+    while let Some(joined) = tasks.join_next().await {
+        match joined? {
+            Ok(()) => aliased_count += 1,
+            Err(e) => eprintln!("Failed to import emoji: {}", e),
+        }
+    }
+
+    println!(
+        "Import complete: {} uploaded, {} aliased, {} skipped, {} failed",
+        uploaded_count,
+        aliased_count,
+        skipped.len(),
+        failed_uploads.len()
+    );
+
     Ok(())
 }
 
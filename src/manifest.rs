@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+
+/// Which operation a manifest belongs to. Export and import are tracked in
+/// separate files so that, e.g., resuming an `import --resume` against a
+/// directory you previously exported into doesn't see every downloaded
+/// name as already "complete" and skip every upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Export,
+    Import,
+}
+
+impl Operation {
+    fn manifest_suffix(self) -> &'static str {
+        match self {
+            Operation::Export => "slack-emoji-exporter-export-manifest.jsonl",
+            Operation::Import => "slack-emoji-exporter-import-manifest.jsonl",
+        }
+    }
+
+    /// Manifests live as dotfiles *next to* `directory` rather than inside
+    /// it: `import` scans every file in `directory` via
+    /// `stream_emoji_files`, and a manifest sitting inside it would be a
+    /// non-emoji file that scan has to somehow know to ignore.
+    fn manifest_path(self, directory: &Path) -> PathBuf {
+        let dir_name = directory
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "emoji".to_string());
+        let parent = directory.parent().unwrap_or_else(|| Path::new("."));
+        parent.join(format!(".{}-{}", dir_name, self.manifest_suffix()))
+    }
+}
+
+/// One line of the manifest: the state of a single emoji's export or
+/// import at the time it was appended. The manifest is the single source
+/// of truth for what's left to do on a `--resume` run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestRecord {
+    pub name: String,
+    pub filename: Option<String>,
+    pub source_url: Option<String>,
+    pub completed: bool,
+}
+
+/// A JSON-lines append log of [`ManifestRecord`]s, plus an in-memory index
+/// of which emoji names are already complete. Safe to share across
+/// concurrent tasks: appends are serialized behind an internal lock.
+#[derive(Debug)]
+pub struct Manifest {
+    completed: RwLock<HashSet<String>>,
+    file: Mutex<File>,
+}
+
+impl Manifest {
+    /// Loads the `operation`-specific manifest from `directory`, replaying
+    /// prior records to rebuild the completed-names index, then reopens it
+    /// for appending. If no manifest exists yet, starts with an empty one.
+    pub async fn load<P: AsRef<Path>>(
+        directory: P,
+        operation: Operation,
+    ) -> Result<Self, Box<dyn Error>> {
+        let path = operation.manifest_path(directory.as_ref());
+        let mut completed = HashSet::new();
+
+        if path.exists() {
+            let file = File::open(&path).await?;
+            let mut lines = BufReader::new(file).lines();
+            while let Some(line) = lines.next_line().await? {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record: ManifestRecord = serde_json::from_str(&line)?;
+                if record.completed {
+                    completed.insert(record.name);
+                } else {
+                    completed.remove(&record.name);
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+
+        Ok(Self {
+            completed: RwLock::new(completed),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Whether `name` was already marked complete in a prior run.
+    pub fn is_complete<T: AsRef<str>>(&self, name: T) -> bool {
+        self.completed.read().unwrap().contains(name.as_ref())
+    }
+
+    /// Appends `record` to the manifest file and, if it's marked complete,
+    /// updates the in-memory index so later `is_complete` checks see it
+    /// immediately.
+    pub async fn append(&self, record: ManifestRecord) -> Result<(), Box<dyn Error>> {
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+
+        {
+            let mut file = self.file.lock().await;
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await?;
+        }
+
+        if record.completed {
+            self.completed.write().unwrap().insert(record.name);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn temp_dir(test_name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "slack-emoji-exporter-manifest-test-{}-{}",
+            test_name,
+            std::process::id()
+        ));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        dir
+    }
+
+    async fn cleanup(dir: &Path) {
+        let _ = tokio::fs::remove_file(Operation::Export.manifest_path(dir)).await;
+        let _ = tokio::fs::remove_file(Operation::Import.manifest_path(dir)).await;
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[test]
+    fn test_manifest_lives_outside_the_scanned_directory() {
+        let dir = Path::new("/tmp/some-export-target");
+        let path = Operation::Export.manifest_path(dir);
+
+        assert!(!path.starts_with(dir));
+        assert_eq!(path.parent(), dir.parent());
+    }
+
+    #[tokio::test]
+    async fn test_manifest_round_trip_survives_reload() {
+        let dir = temp_dir("round-trip").await;
+
+        let manifest = Manifest::load(&dir, Operation::Export).await.unwrap();
+        assert!(!manifest.is_complete("thumbsup"));
+
+        manifest
+            .append(ManifestRecord {
+                name: "thumbsup".to_string(),
+                filename: Some("thumbsup.png".to_string()),
+                source_url: Some("https://example.com/thumbsup.png".to_string()),
+                completed: true,
+            })
+            .await
+            .unwrap();
+        manifest
+            .append(ManifestRecord {
+                name: "partydown".to_string(),
+                filename: Some("partydown.png".to_string()),
+                source_url: None,
+                completed: false,
+            })
+            .await
+            .unwrap();
+
+        assert!(manifest.is_complete("thumbsup"));
+        assert!(!manifest.is_complete("partydown"));
+
+        // A fresh load (as on a resumed run) should replay the same state from disk.
+        let reloaded = Manifest::load(&dir, Operation::Export).await.unwrap();
+        assert!(reloaded.is_complete("thumbsup"));
+        assert!(!reloaded.is_complete("partydown"));
+
+        cleanup(&dir).await;
+    }
+
+    #[tokio::test]
+    async fn test_manifest_is_namespaced_by_operation() {
+        let dir = temp_dir("namespaced").await;
+
+        let export_manifest = Manifest::load(&dir, Operation::Export).await.unwrap();
+        export_manifest
+            .append(ManifestRecord {
+                name: "thumbsup".to_string(),
+                filename: Some("thumbsup.png".to_string()),
+                source_url: Some("https://example.com/thumbsup.png".to_string()),
+                completed: true,
+            })
+            .await
+            .unwrap();
+
+        let import_manifest = Manifest::load(&dir, Operation::Import).await.unwrap();
+        assert!(!import_manifest.is_complete("thumbsup"));
+
+        cleanup(&dir).await;
+    }
+}
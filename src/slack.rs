@@ -1,12 +1,17 @@
+use std::env;
 use std::error::Error;
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use futures::stream::StreamExt;
-use log::{info, trace};
+use log::{info, log, trace, Level};
+use rand::Rng;
 use reqwest::{
     multipart::{Form, Part},
-    Client,
+    Client, Response,
 };
 use serde::Deserialize;
 use tokio::fs::{self, File};
@@ -14,12 +19,36 @@ use tokio::io::AsyncWriteExt;
 use tokio::time::sleep;
 
 use crate::archive::EmojiFile;
+use crate::throttle::Throttle;
+
+/// Set to a `log` level name (e.g. `debug`) to enable request logging
+/// without recompiling, for `SlackClient`s constructed via [`SlackClient::new`]
+/// or [`SlackClient::with_throttle`].
+const REQUEST_LOG_LEVEL_ENV_VAR: &str = "SLACK_EMOJI_EXPORTER_REQUEST_LOG_LEVEL";
+
+/// Base delay used for the exponential backoff fallback when a response
+/// doesn't carry a `retry-after` header.
+const BACKOFF_BASE_MS: u64 = 500;
+/// Upper bound on the exponential backoff delay, so a long run of retries
+/// doesn't end up sleeping for minutes at a time.
+const BACKOFF_CEILING_MS: u64 = 30_000;
+
+/// Default for Slack's `emoji.add` rate tier. Set high enough that it
+/// doesn't itself become the bottleneck under the default `--concurrency`
+/// (see `actions::DEFAULT_CONCURRENCY`) — the bucket's job is to smooth
+/// bursts and back off on real 429s, not to artificially cap throughput
+/// below what concurrency already allows.
+const DEFAULT_REFILL_PER_SEC: f64 = 10.0;
+const DEFAULT_CAPACITY: f64 = 10.0;
 
 #[derive(Debug)]
 pub struct SlackClient {
     pub client: Client,
     pub token: String,
     pub base_url: String,
+    throttle: Throttle,
+    request_log_level: Option<Level>,
+    rate_limit_hits: AtomicU64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,13 +59,50 @@ struct MinimalSlackEndpointResponse {
 
 impl SlackClient {
     pub fn new<S: Into<String>, T: AsRef<str>>(token: S, workspace: T) -> Self {
+        Self::with_throttle(token, workspace, DEFAULT_REFILL_PER_SEC, DEFAULT_CAPACITY)
+    }
+
+    /// Like [`SlackClient::new`], but with the token-bucket's refill rate
+    /// (tokens/second) and burst capacity configurable, for workspaces on a
+    /// different Slack rate tier.
+    pub fn with_throttle<S: Into<String>, T: AsRef<str>>(
+        token: S,
+        workspace: T,
+        refill_per_sec: f64,
+        capacity: f64,
+    ) -> Self {
         Self {
             client: Client::new(),
             token: token.into(),
             base_url: format!("https://{}.slack.com/api", workspace.as_ref()),
+            throttle: Throttle::new(refill_per_sec, capacity),
+            request_log_level: Self::request_log_level_from_env(),
+            rate_limit_hits: AtomicU64::new(0),
         }
     }
 
+    /// Enables structured per-request logging at `level`: each outgoing
+    /// request's method, endpoint and emoji name, each completed response's
+    /// ok/error status and elapsed duration, and, for rate-limited responses,
+    /// the running rate-limit hit count. Overrides whatever `SLACK_EMOJI_EXPORTER_REQUEST_LOG_LEVEL`
+    /// set (if anything).
+    pub fn with_request_logging(mut self, level: Level) -> Self {
+        self.request_log_level = Some(level);
+        self
+    }
+
+    /// The total number of rate-limit (`retry-after`) responses seen so
+    /// far across all requests made by this client.
+    pub fn rate_limit_hits(&self) -> u64 {
+        self.rate_limit_hits.load(Ordering::Relaxed)
+    }
+
+    fn request_log_level_from_env() -> Option<Level> {
+        env::var(REQUEST_LOG_LEVEL_ENV_VAR)
+            .ok()
+            .and_then(|level| Level::from_str(&level).ok())
+    }
+
     pub fn generate_url<T: AsRef<str>>(&self, endpoint: T) -> String {
         format!("{}/{}", self.base_url, endpoint.as_ref())
     }
@@ -62,58 +128,168 @@ impl SlackClient {
         Ok(())
     }
 
-    pub async fn upload(
+    /// Drives a request-producing closure through the shared rate-limit
+    /// retry policy: on a `retry-after` header, sleep for the advertised
+    /// duration and retry; on a transient failure status without one (5xx or
+    /// 429), fall back to exponential backoff with jitter so concurrent
+    /// callers don't all wake up and re-collide at the same instant. Any other
+    /// non-success status is returned as an error without retrying. Retries
+    /// up to `max_attempts` times before giving up.
+    ///
+    /// `endpoint` and `label` (e.g. an emoji name) are only used for the
+    /// opt-in request logging enabled via [`SlackClient::with_request_logging`].
+    async fn with_retry<F, Fut>(
         &self,
-        emoji_file: &EmojiFile,
-        emoji_filepath: PathBuf,
-    ) -> Result<(), Box<dyn Error>> {
+        max_attempts: u8,
+        endpoint: &str,
+        label: &str,
+        f: F,
+    ) -> Result<MinimalSlackEndpointResponse, Box<dyn Error>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Response, reqwest::Error>>,
+    {
         let mut try_count: u8 = 0;
-        let result = loop {
-            // form needs to be recreated on each iteration of the loop since RequestBuilder moves it
-            let form = Form::new()
-                .part("mode", Part::text("data"))
-                // clones are needed here because the values passed to reqwest::multipart::Part's text and file_name methods
-                // are bound by Into<Cow<'static, str>>, so any references passed in would need to have a 'static lifetime.
-                .part("name", Part::text(emoji_file.emoji.name.clone()))
-                .part(
-                    "image",
-                    Part::bytes(fs::read(emoji_filepath.clone()).await?)
-                        .file_name(emoji_file.filename.clone()),
-                )
-                .part("token", Part::text(self.token.clone()));
-
-            let response = self
-                .client
-                .post(&self.generate_url("emoji.add"))
-                .multipart(form)
-                .send()
-                .await?;
-
-            // TODO: if multiple Slack requests rely on handling rate-limiting, could this be better abstracted with a macro?
+        loop {
+            self.throttle.acquire().await;
+
+            if let Some(level) = self.request_log_level {
+                log!(level, "-> POST {} ({})", endpoint, label);
+            }
+            let start = Instant::now();
+            let response = f().await?;
+            let elapsed = start.elapsed();
+
             if let Some(wait_time_s) = response.headers().get("retry-after") {
-                if try_count == 3 {
-                    break Err(format!(
-                        "Could not successfully upload emoji within 3 tries, skipping: {:?}",
-                        emoji_file
-                    ));
-                };
-                try_count += 1;
-                // TODO: better error handling / maybe a better way to go about this?
+                let hits = self.rate_limit_hits.fetch_add(1, Ordering::Relaxed) + 1;
+                if try_count == max_attempts {
+                    return Err(format!(
+                        "Could not successfully complete request within {} retries, giving up",
+                        max_attempts
+                    )
+                    .into());
+                }
                 let wait_time_s: u64 = wait_time_s.to_str()?.parse()?;
                 trace!(
-                    "Hit rate-limit on emoji.add for emoji {}; retrying in {} seconds",
-                    emoji_file.emoji.name,
-                    wait_time_s
+                    "Hit rate-limit; freezing all requests and retrying in {} seconds (attempt {}/{})",
+                    wait_time_s,
+                    try_count + 1,
+                    max_attempts
+                );
+                if let Some(level) = self.request_log_level {
+                    log!(
+                        level,
+                        "<- {} ({}) rate-limited after {:?} (hit #{}); retrying in {}s",
+                        endpoint,
+                        label,
+                        elapsed,
+                        hits,
+                        wait_time_s
+                    );
+                }
+                try_count += 1;
+                self.throttle.freeze_for(Duration::from_secs(wait_time_s));
+                continue;
+            }
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let is_transient =
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+
+                if let Some(level) = self.request_log_level {
+                    log!(
+                        level,
+                        "<- {} ({}) failed with status {} after {:?}",
+                        endpoint,
+                        label,
+                        status,
+                        elapsed
+                    );
+                }
+
+                if !is_transient {
+                    return Err(format!(
+                        "Request failed with non-retryable status {}",
+                        status
+                    )
+                    .into());
+                }
+
+                if try_count == max_attempts {
+                    return Err(format!(
+                        "Request failed with status {} after {} retries, giving up",
+                        status, max_attempts
+                    )
+                    .into());
+                }
+                let backoff = Self::backoff_with_jitter(try_count);
+                trace!(
+                    "Transient error (status {}); backing off for {:?} (attempt {}/{})",
+                    status,
+                    backoff,
+                    try_count + 1,
+                    max_attempts
                 );
-                sleep(Duration::from_secs(wait_time_s)).await;
+                try_count += 1;
+                sleep(backoff).await;
                 continue;
             }
 
-            break Ok(response.json::<MinimalSlackEndpointResponse>().await?);
-        };
+            let response = response.json::<MinimalSlackEndpointResponse>().await?;
+            if let Some(level) = self.request_log_level {
+                log!(
+                    level,
+                    "<- {} ({}) ok={} error={:?} in {:?}",
+                    endpoint,
+                    label,
+                    response.ok,
+                    response.error,
+                    elapsed
+                );
+            }
+            return Ok(response);
+        }
+    }
 
-        // Trying to help avoid consistently hitting a rate limit at a certain point
-        sleep(Duration::from_secs(1)).await;
+    /// `base * 2^attempt`, capped at `BACKOFF_CEILING_MS`, plus a random
+    /// fraction of the capped delay so many concurrent tasks don't retry in
+    /// lockstep.
+    fn backoff_with_jitter(attempt: u8) -> Duration {
+        let exponential = BACKOFF_BASE_MS.saturating_mul(1u64 << attempt.min(32));
+        let capped = exponential.min(BACKOFF_CEILING_MS);
+        let jitter = rand::thread_rng().gen_range(0..=capped / 2);
+        Duration::from_millis(capped + jitter)
+    }
+
+    pub async fn upload(
+        &self,
+        emoji_file: &EmojiFile,
+        emoji_filepath: PathBuf,
+    ) -> Result<(), Box<dyn Error>> {
+        let image_bytes = fs::read(emoji_filepath).await?;
+
+        let result = self
+            .with_retry(3, "emoji.add", &emoji_file.emoji.name, || async {
+                // form needs to be recreated on each attempt since RequestBuilder moves it
+                let form = Form::new()
+                    .part("mode", Part::text("data"))
+                    // clones are needed here because the values passed to reqwest::multipart::Part's text and file_name methods
+                    // are bound by Into<Cow<'static, str>>, so any references passed in would need to have a 'static lifetime.
+                    .part("name", Part::text(emoji_file.emoji.name.clone()))
+                    .part(
+                        "image",
+                        Part::bytes(image_bytes.clone()).file_name(emoji_file.filename.clone()),
+                    )
+                    .part("token", Part::text(self.token.clone()));
+
+                self.client
+                    .post(&self.generate_url("emoji.add"))
+                    .multipart(form)
+                    .send()
+                    .await
+            })
+            .await;
 
         match result {
             Ok(response) => {
@@ -128,7 +304,7 @@ impl SlackClient {
                     Ok(())
                 }
             }
-            Err(e) => Err(e.into()),
+            Err(e) => Err(e),
         }
     }
 
@@ -137,71 +313,41 @@ impl SlackClient {
         name: T,
         alias_for: T,
     ) -> Result<(), Box<dyn Error>> {
-        let mut try_count: u8 = 0;
-        let result = loop {
-            // form needs to be recreated on each iteration of the loop since RequestBuilder moves it
-            let form = Form::new()
-                .part("mode", Part::text("alias"))
-                // clones are needed here because the values passed to reqwest::multipart::Part's text and file_name methods
-                // are bound by Into<Cow<'static, str>>, so any references passed in would need to have a 'static lifetime.
-                .part("name", Part::text(name.as_ref().to_string()))
-                .part("alias_for", Part::text(alias_for.as_ref().to_string()))
-                .part("token", Part::text(self.token.clone()));
-
-            let response = self
-                .client
-                .post(&self.generate_url("emoji.add"))
-                .multipart(form)
-                .send()
-                .await?;
-
-            // TODO: if multiple Slack requests rely on handling rate-limiting, could this be better abstracted with a macro?
-            if let Some(wait_time_s) = response.headers().get("retry-after") {
-                if try_count == 3 {
-                    break Err(format!(
-                        "Could not successfully add alias '{}' for '{}' within 3 tries, skipping",
-                        name.as_ref(),
-                        alias_for.as_ref()
-                    ));
-                };
-                try_count += 1;
-                // TODO: better error handling / maybe a better way to go about this?
-                let wait_time_s: u64 = wait_time_s.to_str()?.parse()?;
-                trace!(
-                    "Hit rate-limit on emoji.add for adding alias '{}' for '{}'; retrying in {} seconds",
-                    name.as_ref(), alias_for.as_ref(),
-                    wait_time_s
-                );
-                sleep(Duration::from_secs(wait_time_s)).await;
-                continue;
-            }
+        let name = name.as_ref();
+        let alias_for = alias_for.as_ref();
+        let label = format!("{} -> {}", name, alias_for);
 
-            break Ok(response.json::<MinimalSlackEndpointResponse>().await?);
-        };
+        let result = self
+            .with_retry(3, "emoji.add", &label, || async {
+                // form needs to be recreated on each attempt since RequestBuilder moves it
+                let form = Form::new()
+                    .part("mode", Part::text("alias"))
+                    .part("name", Part::text(name.to_string()))
+                    .part("alias_for", Part::text(alias_for.to_string()))
+                    .part("token", Part::text(self.token.clone()));
 
-        // Trying to help avoid consistently hitting a rate limit at a certain point
-        sleep(Duration::from_secs(1)).await;
+                self.client
+                    .post(&self.generate_url("emoji.add"))
+                    .multipart(form)
+                    .send()
+                    .await
+            })
+            .await;
 
         match result {
             Ok(response) => {
                 if let Some(error_msg) = response.error {
                     Err(format!(
                         "Failed to add alias '{}' for '{}' for reason: {}",
-                        name.as_ref(),
-                        alias_for.as_ref(),
-                        error_msg
+                        name, alias_for, error_msg
                     )
                     .into())
                 } else {
-                    info!(
-                        "Added alias '{}' for '{}'",
-                        name.as_ref(),
-                        alias_for.as_ref()
-                    );
+                    info!("Added alias '{}' for '{}'", name, alias_for);
                     Ok(())
                 }
             }
-            Err(e) => Err(e.into()),
+            Err(e) => Err(e),
         }
     }
 }
@@ -0,0 +1,157 @@
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+/// A shared token-bucket rate limiter with a cooperative "freeze" gate.
+///
+/// Every caller `acquire`s a token before issuing a request, which
+/// proactively spaces requests out at `refill_per_sec` instead of only
+/// reacting after Slack has already returned a 429. When any caller hits a
+/// `retry-after`, it calls [`Throttle::freeze_for`], which pushes out a
+/// shared "frozen-until" instant; every other in-flight task waits past
+/// that instant before it's allowed to acquire another token, so a single
+/// rate-limit response pauses the whole fleet rather than letting other
+/// tasks pile on and trigger cascading limits.
+#[derive(Debug)]
+pub struct Throttle {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BucketState>,
+    frozen_until: RwLock<Option<Instant>>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Floor applied to a non-positive or non-finite `refill_per_sec`, so the
+/// `deficit / refill_per_sec` wait calculation in `acquire` never divides
+/// by zero (which would compute an infinite `Duration` and panic).
+const MIN_REFILL_PER_SEC: f64 = 0.001;
+
+fn clamped_refill_rate(refill_per_sec: f64) -> f64 {
+    if refill_per_sec.is_finite() && refill_per_sec > 0.0 {
+        refill_per_sec
+    } else {
+        MIN_REFILL_PER_SEC
+    }
+}
+
+impl Throttle {
+    pub fn new(refill_per_sec: f64, capacity: f64) -> Self {
+        Self {
+            capacity,
+            refill_per_sec: clamped_refill_rate(refill_per_sec),
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                last_refill: Instant::now(),
+            }),
+            frozen_until: RwLock::new(None),
+        }
+    }
+
+    /// Waits until the shared freeze window (if any) has passed and a token
+    /// is available, then consumes it.
+    pub async fn acquire(&self) {
+        loop {
+            self.wait_out_freeze().await;
+
+            let wait = {
+                let mut state = self.state.lock().await;
+                self.refill(&mut state);
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    None
+                } else {
+                    let deficit = 1.0 - state.tokens;
+                    Some(Duration::from_secs_f64(deficit / self.refill_per_sec))
+                }
+            };
+
+            match wait {
+                None => return,
+                Some(wait) => sleep(wait).await,
+            }
+        }
+    }
+
+    /// Extends (or starts) the shared freeze window so every task waiting
+    /// on `acquire` pauses until `duration` from now has elapsed.
+    pub fn freeze_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut frozen_until = self.frozen_until.write().unwrap();
+        if frozen_until.map_or(true, |current| until > current) {
+            *frozen_until = Some(until);
+        }
+    }
+
+    async fn wait_out_freeze(&self) {
+        loop {
+            let until = *self.frozen_until.read().unwrap();
+            match until {
+                Some(until) if until > Instant::now() => sleep(until - Instant::now()).await,
+                _ => return,
+            }
+        }
+    }
+
+    fn refill(&self, state: &mut BucketState) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        state.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_throttle_allows_a_burst_up_to_capacity() {
+        let throttle = Throttle::new(1000.0, 2.0);
+
+        let start = Instant::now();
+        throttle.acquire().await;
+        throttle.acquire().await;
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_throttle_waits_for_refill_once_empty() {
+        let throttle = Throttle::new(1000.0, 1.0);
+
+        throttle.acquire().await; // drains the single starting token
+
+        let start = Instant::now();
+        throttle.acquire().await; // must wait ~1ms for the bucket to refill
+
+        assert!(start.elapsed() >= Duration::from_micros(500));
+    }
+
+    #[tokio::test]
+    async fn test_freeze_for_blocks_acquire_until_the_window_passes() {
+        let throttle = Throttle::new(1000.0, 10.0);
+        throttle.freeze_for(Duration::from_millis(50));
+
+        let start = Instant::now();
+        throttle.acquire().await;
+
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_clamped_refill_rate_floors_non_positive_and_non_finite_input() {
+        assert_eq!(clamped_refill_rate(0.0), MIN_REFILL_PER_SEC);
+        assert_eq!(clamped_refill_rate(-5.0), MIN_REFILL_PER_SEC);
+        assert_eq!(clamped_refill_rate(f64::NAN), MIN_REFILL_PER_SEC);
+        assert_eq!(clamped_refill_rate(f64::INFINITY), MIN_REFILL_PER_SEC);
+        assert_eq!(clamped_refill_rate(2.0), 2.0);
+    }
+}